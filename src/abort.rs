@@ -0,0 +1,37 @@
+//! A cheaply-clonable token for shutting down a `Client`/`AsyncClient` from
+//! another thread.
+//!
+//! Without this, a consumer thread blocked in `read_line` or
+//! `thread::sleep` between reconnects has no way to be told to stop short
+//! of being killed outright.
+//!
+//! This is cooperative, not preemptive: `Client::next` only checks
+//! `is_aborted` between lines and while sleeping out a reconnect backoff,
+//! not while actually blocked inside a single `Transport::connect` call or
+//! `BufRead::fill_buf` read. A connection to a server that accepts the
+//! request and then goes silent can still hold the thread for as long as
+//! that one blocking call takes to return - bound it with
+//! `Client::set_connect_timeout` if that matters, since `abort()` alone
+//! can't interrupt it.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Clone)]
+pub struct AbortHandle(Arc<AtomicBool>);
+
+impl AbortHandle {
+    pub fn new() -> AbortHandle {
+        AbortHandle(Arc::new(AtomicBool::new(false)))
+    }
+
+    /// Signal the associated client to stop as soon as it next checks - see
+    /// the module docs for what "next checks" does and doesn't cover.
+    pub fn abort(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
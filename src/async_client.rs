@@ -0,0 +1,234 @@
+//! An async, `futures::Stream`-based alternative to the blocking `Client`.
+//!
+//! `Client`'s `Iterator` impl calls `std::thread::sleep` between reconnect
+//! attempts and blocks on reads, which only works from a dedicated thread.
+//! `AsyncClient` drives the same request/parse/reconnect state machine with
+//! an async HTTP client and a timer future instead, so it can be polled from
+//! inside an event loop (e.g. tokio) alongside other work.
+
+use std::mem;
+use std::time::Duration;
+
+use futures::{Async, Future, Poll, Stream};
+use hyper::{Body, Client as HyperClient, Method, Request};
+use hyper::client::{Connect, FutureResponse, HttpConnector};
+use hyper::header::Headers;
+use tokio_timer::{Sleep, Timer};
+
+use {Event, LastEventID, Url};
+use abort::AbortHandle;
+use backoff::{Backoff, duration_to_millis};
+use decode::LineDecoder;
+use error::Error;
+use parser::{EventParser, ParseResult};
+use transport::validate_content_type;
+
+enum State {
+    // Waiting for the response headers to come back.
+    Connecting(FutureResponse),
+    // Reading and parsing the body of an established stream. The event
+    // accumulates across lines until an empty line dispatches it.
+    Streaming(Body, LineDecoder, Event),
+    // Between connection attempts, waiting for the reconnect delay to elapse.
+    Waiting(Sleep),
+}
+
+/// An async equivalent of `Client`, usable as a `futures::Stream<Item =
+/// Result<Event, Error>>`.
+pub struct AsyncClient<C = HttpConnector>
+    where C: Connect
+{
+    hc: HyperClient<C>,
+    url: Url,
+    parser: EventParser,
+    backoff: Backoff,
+    timer: Timer,
+    state: State,
+    abort: AbortHandle,
+}
+
+impl AsyncClient<HttpConnector> {
+    /// Create a client using hyper's default `HttpConnector`.
+    pub fn new(hc: HyperClient<HttpConnector>, url: Url) -> AsyncClient<HttpConnector> {
+        AsyncClient::with_connector(hc, url)
+    }
+}
+
+impl<C> AsyncClient<C>
+    where C: Connect
+{
+    /// Create a client with a custom hyper connector (e.g. one configured
+    /// for TLS).
+    pub fn with_connector(hc: HyperClient<C>, url: Url) -> AsyncClient<C> {
+        let parser = EventParser::new();
+        let req = AsyncClient::build_request(&hc, &url, &parser);
+        AsyncClient {
+            hc: hc,
+            url: url,
+            parser: parser,
+            backoff: Backoff::new(),
+            timer: Timer::default(),
+            state: State::Connecting(req),
+            abort: AbortHandle::new(),
+        }
+    }
+
+    /// See `Client::set_max_retry`.
+    pub fn set_max_retry(&mut self, max_delay: Duration) -> &mut AsyncClient<C> {
+        self.backoff.set_max_delay_ms(duration_to_millis(max_delay));
+        self
+    }
+
+    /// See `Client::set_backoff`.
+    pub fn set_backoff(&mut self, enabled: bool) -> &mut AsyncClient<C> {
+        self.backoff.set_enabled(enabled);
+        self
+    }
+
+    /// See `Client::set_max_event_size`.
+    pub fn set_max_event_size(&mut self, max_event_size: Option<usize>) -> &mut AsyncClient<C> {
+        self.parser.set_max_event_size(max_event_size);
+        self
+    }
+
+    /// See `Client::abort_handle`.
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.abort.clone()
+    }
+
+    fn build_request(hc: &HyperClient<C>, url: &Url, parser: &EventParser) -> FutureResponse {
+        let mut headers = Headers::new();
+        if let Some(ref id) = parser.last_event_id {
+            headers.set(LastEventID(id.clone()));
+        }
+        let uri = url.as_str().parse().expect("Url is already a valid Uri");
+        let mut req = Request::new(Method::Get, uri);
+        *req.headers_mut() = headers;
+        hc.request(req)
+    }
+
+    fn wait_state(&mut self) -> State {
+        let delay_ms = self.backoff.next_delay_ms(self.parser.retry);
+        State::Waiting(self.timer.sleep(Duration::from_millis(delay_ms)))
+    }
+}
+
+// What to do once the current state has run its course.
+enum Action {
+    Transition(State),
+    // The connection attempt failed or the stream ended; wait out the
+    // reconnect delay before trying again.
+    Wait,
+    // The reconnect delay elapsed; issue a new request right away.
+    Reconnect,
+}
+
+impl<C> Stream for AsyncClient<C>
+    where C: Connect
+{
+    // Like the blocking `Iterator`, errors are yielded as items rather than
+    // ending the stream: a dropped connection is just another reason to
+    // reconnect.
+    type Item = Result<Event, Error>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Result<Event, Error>>, Error> {
+        loop {
+            if self.abort.is_aborted() {
+                return Ok(Async::Ready(None));
+            }
+            let action = match self.state {
+                State::Waiting(ref mut sleep) => {
+                    match sleep.poll() {
+                        Ok(Async::Ready(())) => Action::Reconnect,
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(_) => Action::Reconnect, // timer shutdown; just try again
+                    }
+                }
+                State::Connecting(ref mut fut) => {
+                    match fut.poll() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(res)) => {
+                            let status = res.status();
+                            if !status.is_success() {
+                                self.state = self.wait_state();
+                                return Ok(Async::Ready(Some(Err(Error::Http(status)))));
+                            }
+                            let content_type = res.headers().get_raw("Content-Type")
+                                .and_then(|raw| raw.one())
+                                .and_then(|bytes| ::std::str::from_utf8(bytes).ok());
+                            match validate_content_type(content_type) {
+                                Ok(()) => Action::Transition(
+                                    State::Streaming(res.body(), LineDecoder::new(), Event::new())),
+                                Err(err) => {
+                                    self.state = self.wait_state();
+                                    return Ok(Async::Ready(Some(Err(err))));
+                                }
+                            }
+                        }
+                        Err(err) => {
+                            self.state = self.wait_state();
+                            return Ok(Async::Ready(Some(Err(Error::from(err)))));
+                        }
+                    }
+                }
+                State::Streaming(ref mut body, ref mut decoder, ref mut event) => {
+                    if let Some(line) = decoder.next_line() {
+                        match self.parser.parse_line(&line, event) {
+                            ParseResult::Dispatch => {
+                                self.backoff.reset();
+                                let event = mem::replace(event, Event::new());
+                                return Ok(Async::Ready(Some(Ok(event))));
+                            }
+                            ParseResult::Next => (),
+                            ParseResult::TooLarge => {
+                                *event = Event::new();
+                                return Ok(Async::Ready(Some(Err(Error::EventTooLarge))));
+                            }
+                        }
+                        continue;
+                    }
+                    match body.poll() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(Some(chunk))) => {
+                            decoder.feed(&chunk);
+                            continue;
+                        }
+                        Ok(Async::Ready(None)) => {
+                            // EOF: parse a final unterminated line, if any. It
+                            // dispatches only if it is the blank line ending an
+                            // event; an event still incomplete is discarded.
+                            if let Some(line) = decoder.finish() {
+                                match self.parser.parse_line(&line, event) {
+                                    ParseResult::Dispatch => {
+                                        self.backoff.reset();
+                                        let event = mem::replace(event, Event::new());
+                                        return Ok(Async::Ready(Some(Ok(event))));
+                                    }
+                                    ParseResult::Next => (),
+                                    ParseResult::TooLarge => {
+                                        *event = Event::new();
+                                        return Ok(Async::Ready(Some(Err(Error::EventTooLarge))));
+                                    }
+                                }
+                            }
+                            Action::Wait
+                        }
+                        Err(err) => {
+                            self.state = self.wait_state();
+                            return Ok(Async::Ready(Some(Err(Error::from(err)))));
+                        }
+                    }
+                }
+            };
+            match action {
+                Action::Transition(state) => self.state = state,
+                Action::Wait => self.state = self.wait_state(),
+                Action::Reconnect => {
+                    let req = AsyncClient::build_request(&self.hc, &self.url, &self.parser);
+                    self.state = State::Connecting(req);
+                }
+            }
+        }
+    }
+}
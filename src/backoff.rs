@@ -0,0 +1,82 @@
+//! Reconnect backoff policy shared by `Client` and `AsyncClient`.
+//!
+//! `Iterator::next` used to sleep for exactly `retry` ms on EOF and give up
+//! immediately (no delay at all) on a non-success status or transport
+//! error, which makes a flaky or misbehaving server trigger a hot
+//! reconnect loop. `Backoff` instead doubles the delay on each consecutive
+//! failure, clamps it to `max_delay`, and adds jitter so many clients
+//! reconnecting to the same server don't all retry in lockstep.
+
+use rand::Rng;
+use rand;
+
+use std::cmp;
+use std::time::Duration;
+
+/// Convert a `Duration` to whole milliseconds, saturating rather than
+/// overflowing for absurdly large durations.
+pub fn duration_to_millis(d: Duration) -> u64 {
+    d.as_secs().saturating_mul(1000).saturating_add((d.subsec_nanos() / 1_000_000) as u64)
+}
+
+/// Default ceiling the doubled reconnect delay is clamped to, regardless of
+/// how many consecutive failures have occurred. Jitter is applied after
+/// clamping, so a single wait can be up to 1.5x this.
+pub const DEFAULT_MAX_DELAY_MS: u64 = 60_000;
+
+/// Default cap on the doubling exponent, i.e. the delay never grows past
+/// `base * 2^DEFAULT_CAP_EXPONENT` (before clamping to `max_delay`).
+pub const DEFAULT_CAP_EXPONENT: u32 = 6;
+
+pub struct Backoff {
+    failures: u32,
+    max_delay_ms: u64,
+    cap_exponent: u32,
+    enabled: bool,
+}
+
+impl Backoff {
+    pub fn new() -> Backoff {
+        Backoff {
+            failures: 0,
+            max_delay_ms: DEFAULT_MAX_DELAY_MS,
+            cap_exponent: DEFAULT_CAP_EXPONENT,
+            enabled: true,
+        }
+    }
+
+    pub fn set_max_delay_ms(&mut self, max_delay_ms: u64) {
+        self.max_delay_ms = max_delay_ms;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Call after an event is successfully dispatched, so a brief blip
+    /// doesn't leave future reconnects backed off.
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Record a failed connection attempt and return how long to wait
+    /// before the next one, in milliseconds. `base` is the current
+    /// reconnection time (the server's `retry:` field, or `DEFAULT_RETRY`).
+    pub fn next_delay_ms(&mut self, base: u64) -> u64 {
+        if !self.enabled {
+            return base;
+        }
+        let exponent = cmp::min(self.failures, self.cap_exponent);
+        self.failures = self.failures.saturating_add(1);
+        let delay = base.saturating_mul(1u64 << exponent);
+        let delay = cmp::min(delay, self.max_delay_ms);
+        jitter(delay)
+    }
+}
+
+// +/-50% jitter, to avoid a thundering herd of clients reconnecting to the
+// same server in lockstep.
+fn jitter(delay_ms: u64) -> u64 {
+    let factor = rand::thread_rng().gen_range(0.5, 1.5);
+    (delay_ms as f64 * factor) as u64
+}
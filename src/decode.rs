@@ -0,0 +1,165 @@
+//! Byte-level line splitting for `text/event-stream` bodies.
+//!
+//! `BufRead::read_line` only stops at `\n`, but the SSE grammar treats
+//! `\r`, `\n`, and `\r\n` as equivalent line terminators, and a leading
+//! UTF-8 BOM must be stripped once before the first line. Splitting on raw
+//! bytes (instead of decoding each chunk to `String` independently, as the
+//! async client used to) also means a multi-byte UTF-8 character split
+//! across two reads is never corrupted: a line's bytes are only decoded
+//! once they're all in hand, and the terminator bytes themselves are
+//! always ASCII so they can't occur inside a multi-byte sequence.
+
+use std::cmp;
+
+const BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
+
+pub struct LineDecoder {
+    buf: Vec<u8>,
+    stripped_bom: bool,
+}
+
+impl LineDecoder {
+    pub fn new() -> LineDecoder {
+        LineDecoder {
+            buf: Vec::new(),
+            stripped_bom: false,
+        }
+    }
+
+    /// Buffer newly-read bytes.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Pull the next complete, terminator-stripped line out of the
+    /// buffered bytes. Returns `None` if no full line is buffered yet;
+    /// `feed` more bytes and try again.
+    pub fn next_line(&mut self) -> Option<String> {
+        self.strip_bom(false);
+        let mut i = 0;
+        while i < self.buf.len() {
+            match self.buf[i] {
+                b'\n' => {
+                    let line = decode(&self.buf[..i]);
+                    self.buf.drain(..i + 1);
+                    return Some(line);
+                }
+                b'\r' => {
+                    // This might be the start of "\r\n"; if the next byte
+                    // hasn't arrived yet, wait rather than guess.
+                    if i + 1 == self.buf.len() {
+                        return None;
+                    }
+                    let end = if self.buf[i + 1] == b'\n' { i + 1 } else { i };
+                    let line = decode(&self.buf[..i]);
+                    self.buf.drain(..end + 1);
+                    return Some(line);
+                }
+                _ => i += 1,
+            }
+        }
+        None
+    }
+
+    /// Flush whatever's left as a final, unterminated line once the
+    /// stream has ended. Returns `None` if nothing is buffered.
+    pub fn finish(&mut self) -> Option<String> {
+        self.strip_bom(true);
+        if self.buf.is_empty() {
+            None
+        } else {
+            // A bare trailing "\r" can only be the last byte buffered: any
+            // "\r" seen mid-buffer is resolved (as "\r" or "\r\n") and
+            // drained by next_line before more bytes are fed. Strip it so
+            // an EOF right after a lone CR doesn't leak it into the line.
+            if self.buf.last() == Some(&b'\r') {
+                self.buf.pop();
+            }
+            let line = decode(&self.buf);
+            self.buf.clear();
+            Some(line)
+        }
+    }
+
+    // Strip a leading BOM exactly once, at the very start of the stream. A
+    // BOM can itself be split across reads, so while the buffered bytes
+    // are still a prefix of it we hold off deciding (returning without
+    // setting `stripped_bom`, so lines can't be emitted out from under an
+    // undecided BOM); as soon as they diverge from the BOM, fill it out,
+    // or the stream ends, the question is settled for good and this never
+    // runs again - so a later chunk that happens to start with EF BB BF
+    // can't be mistaken for a BOM once the stream is already under way.
+    fn strip_bom(&mut self, eof: bool) {
+        if self.stripped_bom {
+            return;
+        }
+        let prefix_len = cmp::min(self.buf.len(), BOM.len());
+        if self.buf[..prefix_len] != BOM[..prefix_len] {
+            self.stripped_bom = true;
+        } else if self.buf.len() >= BOM.len() {
+            self.buf.drain(..BOM.len());
+            self.stripped_bom = true;
+        } else if eof {
+            self.stripped_bom = true;
+        }
+    }
+}
+
+fn decode(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bare_cr_terminates_a_line() {
+        let mut d = LineDecoder::new();
+        d.feed(b"data: a\rdata: b\r\n");
+        assert_eq!(Some("data: a".to_string()), d.next_line());
+        assert_eq!(Some("data: b".to_string()), d.next_line());
+        assert_eq!(None, d.next_line());
+    }
+
+    #[test]
+    fn bom_split_across_two_feeds() {
+        let mut d = LineDecoder::new();
+        d.feed(&[0xEF, 0xBB]);
+        assert_eq!(None, d.next_line());
+        d.feed(&[0xBF]);
+        d.feed(b"data: hello\n");
+        assert_eq!(Some("data: hello".to_string()), d.next_line());
+    }
+
+    #[test]
+    fn short_leading_line_does_not_leave_bom_check_pending() {
+        // A keep-alive "\n" as the very first chunk resolves "no BOM"
+        // immediately, even though fewer than 3 bytes were ever buffered -
+        // so a later chunk that happens to start with EF BB BF is just
+        // data, not a stray mid-stream BOM.
+        let mut d = LineDecoder::new();
+        d.feed(b"\n");
+        assert_eq!(Some("".to_string()), d.next_line());
+        d.feed(&[0xEF, 0xBB, 0xBF, b'\n']);
+        assert_eq!(Some("\u{feff}".to_string()), d.next_line());
+    }
+
+    #[test]
+    fn finish_strips_a_trailing_bare_cr() {
+        let mut d = LineDecoder::new();
+        d.feed(b"data: x\r");
+        assert_eq!(None, d.next_line());
+        assert_eq!(Some("data: x".to_string()), d.finish());
+    }
+
+    #[test]
+    fn multibyte_char_split_across_read_boundary() {
+        // U+00E9 ('\u{e9}') encodes as the two bytes 0xC3 0xA9.
+        let mut d = LineDecoder::new();
+        d.feed(&[b'd', b'a', b't', b'a', b':', b' ', 0xC3]);
+        assert_eq!(None, d.next_line());
+        d.feed(&[0xA9, b'\n']);
+        assert_eq!(Some("data: \u{e9}".to_string()), d.next_line());
+    }
+}
@@ -0,0 +1,85 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use hyper;
+use hyper::status::StatusCode;
+use mime::Mime;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The server responded with a non-success HTTP status.
+    Http(StatusCode),
+    /// The response's `Content-Type` was present but not `text/event-stream`.
+    InvalidContentType(Mime),
+    /// The response had no `Content-Type` header at all.
+    NoContentType,
+    /// The response's `Content-Type` header was present but could not be
+    /// parsed as a MIME type at all.
+    MalformedContentType(String),
+    /// A single event's fields grew past the configured `max_event_size`;
+    /// the partial event was dropped.
+    EventTooLarge,
+    /// The connect path followed more redirects than the configured limit.
+    TooManyRedirects,
+    /// The initial URL or a redirect `Location` could not be parsed as a URI.
+    InvalidUrl(String),
+    /// An error occurred in the underlying HTTP client.
+    Hyper(hyper::Error),
+    /// An error occurred while reading from the response body.
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Http(ref status) => write!(f, "HTTP error: {}", status),
+            Error::InvalidContentType(ref mime) => write!(f, "invalid Content-Type: {}", mime),
+            Error::NoContentType => write!(f, "response had no Content-Type header"),
+            Error::MalformedContentType(ref value) => write!(f, "unparseable Content-Type: {}", value),
+            Error::EventTooLarge => write!(f, "event exceeded the configured max_event_size"),
+            Error::TooManyRedirects => write!(f, "exceeded the configured redirect limit"),
+            Error::InvalidUrl(ref url) => write!(f, "invalid URL: {}", url),
+            Error::Hyper(ref err) => write!(f, "hyper error: {}", err),
+            Error::Io(ref err) => write!(f, "IO error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Http(_) => "server responded with a non-success status",
+            Error::InvalidContentType(_) => "response Content-Type was not text/event-stream",
+            Error::NoContentType => "response had no Content-Type header",
+            Error::MalformedContentType(_) => "response Content-Type could not be parsed",
+            Error::EventTooLarge => "event exceeded the configured max_event_size",
+            Error::TooManyRedirects => "exceeded the configured redirect limit",
+            Error::InvalidUrl(_) => "URL could not be parsed as a URI",
+            Error::Hyper(ref err) => err.description(),
+            Error::Io(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Http(_) | Error::InvalidContentType(_) | Error::NoContentType
+                | Error::MalformedContentType(_) | Error::InvalidUrl(_)
+                | Error::EventTooLarge | Error::TooManyRedirects => None,
+            Error::Hyper(ref err) => Some(err),
+            Error::Io(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(err: hyper::Error) -> Error {
+        Error::Hyper(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
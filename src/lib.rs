@@ -1,27 +1,54 @@
 #[macro_use] extern crate hyper;
+extern crate mime;
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_timer;
+extern crate rand;
+extern crate url;
 
+mod abort;
+mod decode;
 mod error;
+mod parser;
+mod async_client;
+mod transport;
+mod backoff;
 
+pub use abort::AbortHandle;
+pub use async_client::AsyncClient;
+pub use transport::Transport;
+#[cfg(feature = "with-hyper")]
+pub use transport::HyperTransport;
+use backoff::{Backoff, duration_to_millis};
+use decode::LineDecoder;
 use error::Error;
+use parser::{EventParser, ParseResult};
+use transport::DefaultTransport;
 
+use std::cmp;
 use std::fmt;
-use std::io::{BufRead, BufReader};
+use std::io::BufRead;
 use std::time::Duration;
-use hyper::client::{Client as HyperClient};
-use hyper::client::response::Response;
-use hyper::header::Headers;
-use hyper::Url;
+use url::Url;
+
+// Upper bound on each slice of `wait_before_retry`'s sleep, so an abort
+// during a long backoff delay (by default up to 1.5x DEFAULT_MAX_DELAY_MS,
+// once jitter is added) takes effect
+// promptly instead of only being noticed once the whole delay has elapsed.
+const ABORT_POLL_INTERVAL: Duration = Duration::from_millis(200);
 
 const DEFAULT_RETRY: u64 = 5000;
 
 header! { (LastEventID, "Last-Event-ID") => [String] }
 
-pub struct Client {
-    hc: HyperClient,
-    reader: Option<BufReader<Response>>,
+pub struct Client<T: Transport = DefaultTransport> {
+    transport: T,
+    reader: Option<T::Reader>,
+    decoder: LineDecoder,
     url: Url,
-    last_event_id: Option<String>,
-    retry: u64, // reconnection time in milliseconds
+    parser: EventParser,
+    backoff: Backoff,
+    abort: AbortHandle,
 }
 
 #[derive(Debug)]
@@ -31,101 +58,175 @@ pub struct Event {
     pub data: String,
 }
 
-enum ParseResult {
-    Next,
-    Dispatch,
+impl Client<DefaultTransport> {
+    pub fn new(url: Url) -> Client<DefaultTransport> {
+        Client::with_transport(DefaultTransport::new(), url)
+    }
 }
 
-impl Client {
-    pub fn new(url: Url) -> Client {
+impl<T: Transport> Client<T> {
+    pub fn with_transport(transport: T, url: Url) -> Client<T> {
         Client {
-            hc: HyperClient::new(),
+            transport: transport,
             reader: None,
+            decoder: LineDecoder::new(),
             url: url,
-            last_event_id: None,
-            retry: DEFAULT_RETRY,
+            parser: EventParser::new(),
+            backoff: Backoff::new(),
+            abort: AbortHandle::new(),
         }
     }
 
-    fn next_request(&self) -> hyper::error::Result<Response> {
-        let mut headers = Headers::new();
-        if let Some(ref id) = self.last_event_id {
-            headers.set(LastEventID(id.clone()));
-        }
-        self.hc.get(self.url.clone()).headers(headers).send()
+    /// Set the ceiling the exponential backoff clamps the reconnect delay
+    /// to, regardless of how many consecutive failures have occurred. The
+    /// +/-50% jitter is applied after clamping, so a single wait can be up
+    /// to 1.5x this. Has no effect while backoff is disabled.
+    pub fn set_max_retry(&mut self, max_delay: Duration) -> &mut Client<T> {
+        self.backoff.set_max_delay_ms(duration_to_millis(max_delay));
+        self
     }
 
-    fn parse_event_line(&mut self, line: &str, event: &mut Event) -> ParseResult {
-        let line = if line.ends_with('\n') { &line[0..line.len()-1] } else { line };
-        if line == "" {
-            ParseResult::Dispatch
-        } else {
-            let (field, value) = if let Some(pos) = line.find(':') {
-                let (f, v) = line.split_at(pos);
-                // Strip : and an optional space.
-                let v = &v[1..];
-                let v = if v.starts_with(' ') { &v[1..] } else { v };
-                (f, v)
-            } else {
-                (line, "")
-            };
-            
-            match field {
-                "event" => { event.event_type = Some(value.to_string()); },
-                "data" => { event.data.push_str(value); event.data.push('\n'); },
-                "id" => { event.id = Some(value.to_string()); self.last_event_id = Some(value.to_string()); }
-                "retry" => {
-                    if let Ok(retry) = value.parse::<u64>() {
-                        self.retry = retry;
-                    }
-                },
-                _ => () // ignored
-            }
+    /// Enable or disable exponential backoff. When disabled, every
+    /// reconnect waits exactly the current `retry` time (the server's
+    /// `retry:` field, or `DEFAULT_RETRY`), matching the old fixed-delay
+    /// behavior.
+    pub fn set_backoff(&mut self, enabled: bool) -> &mut Client<T> {
+        self.backoff.set_enabled(enabled);
+        self
+    }
 
-            ParseResult::Next
-        }
+    /// Drop an in-progress event once its fields (`data`/`event`/`id`
+    /// combined) grow past `max_event_size` bytes, yielding
+    /// `Error::EventTooLarge` instead of buffering it unboundedly. Pass
+    /// `None` to disable the limit (the default).
+    pub fn set_max_event_size(&mut self, max_event_size: Option<usize>) -> &mut Client<T> {
+        self.parser.set_max_event_size(max_event_size);
+        self
+    }
+
+    /// Cap the number of redirects followed when connecting. Not every
+    /// `Transport` can honor this.
+    pub fn set_max_redirects(&mut self, max_redirects: u32) -> &mut Client<T> {
+        self.transport.set_max_redirects(max_redirects);
+        self
+    }
+
+    /// Set (or clear) the connect/read timeout. Not every `Transport` can
+    /// honor this.
+    pub fn set_connect_timeout(&mut self, timeout: Option<Duration>) -> &mut Client<T> {
+        self.transport.set_timeout(timeout);
+        self
+    }
+
+    /// A cheaply-clonable token that, once `abort()`-ed, causes this client
+    /// to stop reading/reconnecting and its `Iterator` to return `None` at
+    /// the next opportunity. See `AbortHandle`'s docs for what counts as an
+    /// opportunity - it can't interrupt a blocking `Transport::connect` or
+    /// body read already in progress.
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.abort.clone()
     }
-}
 
-// Helper macro for Option<Result<...>>
-macro_rules! try_option {
-    ($e:expr) => (match $e {
-        Ok(val) => val,
-        Err(err) => return Some(Err(::std::convert::From::from(err))),
-    });
+    // Wait out the current backoff delay before the caller's next attempt,
+    // checking `abort` periodically so a long delay doesn't swallow an
+    // abort request. This can't help once we're back inside `fill_buf` or
+    // `Transport::connect` on a stalled connection; see `AbortHandle`'s
+    // docs for that limitation.
+    fn wait_before_retry(&mut self) {
+        let mut remaining = Duration::from_millis(self.backoff.next_delay_ms(self.parser.retry));
+        while remaining > Duration::from_millis(0) {
+            if self.abort.is_aborted() {
+                return;
+            }
+            let slice = cmp::min(remaining, ABORT_POLL_INTERVAL);
+            std::thread::sleep(slice);
+            remaining -= slice;
+        }
+    }
 }
 
 // Iterate over the client to get events.
-impl Iterator for Client {
+impl<T: Transport> Iterator for Client<T> {
     type Item = Result<Event, Error>;
 
     fn next(&mut self) -> Option<Result<Event, Error>> {
-        if self.reader.is_none() {
-            let req = try_option!(self.next_request());
-            // We can only work with successful requests.
-            // TODO: Should honor the `retry` timeout for the next iteration.
-            if !req.status.is_success() {
-                return Some(Err(Error::Http(req.status)));
+        // A reconnect just loops back around to the top instead of
+        // recursing, so a long-lived stream that reconnects often (e.g. a
+        // server that accepts then immediately EOFs) doesn't grow the stack.
+        loop {
+            if self.abort.is_aborted() {
+                return None;
             }
-            let r = BufReader::new(req);
-            self.reader = Some(r);
-        }
-        let mut event = Event::new();
-        let mut line = String::new();
-
-        // We can't have a mutable reference to the reader because of the &mut self call below.
-        // The first unwrap() is safe as we're checking that above.
-        while try_option!(self.reader.as_mut().unwrap().read_line(&mut line)) > 0 {
-            match self.parse_event_line(&line, &mut event) {
-                ParseResult::Dispatch => return Some(Ok(event)),
-                ParseResult::Next => (),
+            if self.reader.is_none() {
+                let last_event_id = self.parser.last_event_id.clone();
+                match self.transport.connect(&self.url, last_event_id.as_ref().map(|s| s.as_str())) {
+                    Ok(r) => {
+                        self.reader = Some(r);
+                        self.decoder = LineDecoder::new();
+                    }
+                    Err(err) => {
+                        // Honor the backoff delay instead of giving up outright.
+                        self.wait_before_retry();
+                        return Some(Err(err));
+                    }
+                }
+            }
+            let mut event = Event::new();
+
+            loop {
+                if self.abort.is_aborted() {
+                    return None;
+                }
+                if let Some(line) = self.decoder.next_line() {
+                    match self.parser.parse_line(&line, &mut event) {
+                        ParseResult::Dispatch => {
+                            self.backoff.reset();
+                            return Some(Ok(event));
+                        }
+                        ParseResult::Next => (),
+                        ParseResult::TooLarge => return Some(Err(Error::EventTooLarge)),
+                    }
+                    continue;
+                }
+                // We can't have a mutable reference to the reader because of the &mut self calls below.
+                // The first unwrap() is safe as we're checking that above.
+                let read = match self.reader.as_mut().unwrap().fill_buf() {
+                    Ok(buf) => {
+                        self.decoder.feed(buf);
+                        buf.len()
+                    }
+                    Err(err) => {
+                        self.reader = None;
+                        self.wait_before_retry();
+                        return Some(Err(Error::from(err)));
+                    }
+                };
+                if read == 0 {
+                    break; // EOF
+                }
+                self.reader.as_mut().unwrap().consume(read);
+            }
+            // EOF: parse a final unterminated line, if any (it dispatches
+            // only if it is the blank line ending an event; an event still
+            // incomplete is discarded), then retry after the backoff delay.
+            if let Some(line) = self.decoder.finish() {
+                match self.parser.parse_line(&line, &mut event) {
+                    ParseResult::Dispatch => {
+                        self.backoff.reset();
+                        self.reader = None;
+                        return Some(Ok(event));
+                    }
+                    ParseResult::Next => (),
+                    ParseResult::TooLarge => {
+                        self.reader = None;
+                        return Some(Err(Error::EventTooLarge));
+                    }
+                }
             }
-            line.clear();
+            self.reader = None;
+            self.wait_before_retry();
+            // Loop back around and try reconnecting rather than recursing.
         }
-        // EOF, retry after timeout
-        self.reader = None;
-        std::thread::sleep(Duration::from_millis(self.retry));
-        self.next()
     }
 }
 
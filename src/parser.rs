@@ -0,0 +1,95 @@
+//! Transport-agnostic parsing of the `text/event-stream` line protocol.
+//!
+//! Both the blocking `Client` and the async `AsyncClient` read lines from
+//! whatever transport they use and hand them to an `EventParser`, so the
+//! field-dispatch rules and `last_event_id`/`retry` bookkeeping only need to
+//! be implemented (and tested) once.
+
+use Event;
+
+use DEFAULT_RETRY;
+
+pub enum ParseResult {
+    Next,
+    Dispatch,
+    // The event being accumulated grew past `max_event_size`; it was
+    // dropped and the caller should surface `Error::EventTooLarge`.
+    TooLarge,
+}
+
+pub struct EventParser {
+    pub last_event_id: Option<String>,
+    pub retry: u64, // reconnection time in milliseconds
+    max_event_size: Option<usize>,
+    // Set once a TooLarge event is dropped; swallows the remaining lines
+    // of that event (without touching the caller's fresh `Event`) until
+    // the blank line that would have dispatched it is reached.
+    dropping: bool,
+}
+
+impl EventParser {
+    pub fn new() -> EventParser {
+        EventParser {
+            last_event_id: None,
+            retry: DEFAULT_RETRY,
+            max_event_size: None,
+            dropping: false,
+        }
+    }
+
+    pub fn set_max_event_size(&mut self, max_event_size: Option<usize>) {
+        self.max_event_size = max_event_size;
+    }
+
+    pub fn parse_line(&mut self, line: &str, event: &mut Event) -> ParseResult {
+        let line = if line.ends_with('\n') { &line[0..line.len()-1] } else { line };
+        if self.dropping {
+            if line == "" {
+                // The dropped event's own dispatch boundary: consume it
+                // silently rather than dispatching the (empty) fresh event.
+                self.dropping = false;
+            }
+            return ParseResult::Next;
+        }
+        if line == "" {
+            ParseResult::Dispatch
+        } else {
+            let (field, value) = if let Some(pos) = line.find(':') {
+                let (f, v) = line.split_at(pos);
+                // Strip : and an optional space.
+                let v = &v[1..];
+                let v = if v.starts_with(' ') { &v[1..] } else { v };
+                (f, v)
+            } else {
+                (line, "")
+            };
+
+            if let Some(max) = self.max_event_size {
+                // Only the fields stored on the event count toward the limit.
+                if field == "data" || field == "event" || field == "id" {
+                    let current = event.data.len()
+                        + event.event_type.as_ref().map_or(0, String::len)
+                        + event.id.as_ref().map_or(0, String::len);
+                    if current + value.len() > max {
+                        self.dropping = true;
+                        return ParseResult::TooLarge;
+                    }
+                }
+            }
+
+            match field {
+                "event" => { event.event_type = Some(value.to_string()); },
+                "data" => { event.data.push_str(value); event.data.push('\n'); },
+                "id" => { event.id = Some(value.to_string()); self.last_event_id = Some(value.to_string()); }
+                "retry" => {
+                    if let Ok(retry) = value.parse::<u64>() {
+                        self.retry = retry;
+                    }
+                },
+                _ => () // ignored
+            }
+
+            ParseResult::Next
+        }
+    }
+}